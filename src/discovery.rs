@@ -6,18 +6,142 @@ use crate::{Event, UsbHost};
 use usb_device::control::Recipient;
 use defmt::trace;
 
+/// Upper bound on the number of characters decoded out of a string
+/// descriptor payload. USB string descriptors are capped at 255 bytes
+/// (including the 2 byte header), which comfortably bounds the UTF-16
+/// payload under this.
+const MAX_STRING_CHARS: usize = 126;
+
+/// Upper bound on the number of configurations whose `bConfigurationValue`
+/// is tracked across the `ConfigDesc` loop for the `configure()` callback.
+const MAX_CONFIGURATIONS: usize = 8;
+
+/// Retry budget for a single discovery state: how many times a request is
+/// attempted in total. The request is abandoned on its `MAX_ATTEMPTS`-th
+/// STALL or missing response, so `MAX_ATTEMPTS - 1` failures are retried.
+const MAX_ATTEMPTS: u8 = 3;
+
+/// `bConfigurationValue`s (and their `wTotalLength`) collected from each
+/// configuration's short descriptor, handed to drivers via
+/// `Driver::configure` once the configuration phase is over. If an optional
+/// configuration (any but the first) cannot be read, it and the ones after it
+/// are left out.
+#[derive(Copy, Clone, Debug)]
+pub struct ConfigurationValues {
+    values: [u8; MAX_CONFIGURATIONS],
+    lengths: [u16; MAX_CONFIGURATIONS],
+    len: u8,
+}
+
+impl ConfigurationValues {
+    fn new() -> Self {
+        Self {
+            values: [0; MAX_CONFIGURATIONS],
+            lengths: [0; MAX_CONFIGURATIONS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: u8, total_length: u16) {
+        if (self.len as usize) < MAX_CONFIGURATIONS {
+            self.values[self.len as usize] = value;
+            self.lengths[self.len as usize] = total_length;
+            self.len += 1;
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.values[..self.len as usize]
+    }
+
+    /// Position of `value` among the configurations seen so far, if any.
+    fn position_of(&self, value: u8) -> Option<u8> {
+        self.as_slice().iter().position(|&v| v == value).map(|i| i as u8)
+    }
+
+    /// The `(bConfigurationValue, wTotalLength)` pair at `index`.
+    fn entry(&self, index: u8) -> (u8, u16) {
+        (self.values[index as usize], self.lengths[index as usize])
+    }
+}
+
+/// Bitmask over the `drivers` slice, one bit per driver index, tracking
+/// which drivers claimed at least one interface in the configuration that
+/// was actually selected by `SET_CONFIGURATION`. Only the first 32 drivers
+/// are eligible to claim; a `drivers` slice longer than that is silently
+/// capped rather than risking a shift overflow, which comfortably covers
+/// any realistic host stack.
+type ClaimedDrivers = u32;
+
+/// Pending string-descriptor indices captured off the device descriptor,
+/// plus the configuration count needed to resume discovery once string
+/// fetching is done.
+#[derive(Copy, Clone, Debug)]
+pub struct StringContext {
+    // iManufacturer, iProduct, iSerialNumber, in fetch order.
+    indices: [u8; 3],
+    cursor: u8,
+    num_configurations: u8,
+}
+
+impl StringContext {
+    fn new(
+        i_manufacturer: u8,
+        i_product: u8,
+        i_serial_number: u8,
+        num_configurations: u8,
+    ) -> Self {
+        Self {
+            indices: [i_manufacturer, i_product, i_serial_number],
+            cursor: 0,
+            num_configurations,
+        }
+    }
+
+    /// Returns the next nonzero string index still to be fetched, if any.
+    fn next_index(&mut self) -> Option<u8> {
+        while (self.cursor as usize) < self.indices.len() {
+            let index = self.indices[self.cursor as usize];
+            self.cursor += 1;
+            if index != 0 {
+                return Some(index);
+            }
+        }
+        None
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum DiscoveryState {
-    // get device descriptor
-    DeviceDesc,
-    // get configuration descriptor length n of m
-    ConfigDescLen(u8, u8),
-    // get full configuration descriptor n of m
-    ConfigDesc(u8, u8),
+    // get device descriptor. u8: attempts so far.
+    DeviceDesc(u8),
+    // get the supported LANGIDs via string descriptor 0
+    StringLangId(StringContext),
+    // get string descriptor length for the given index
+    StringDescLen(StringContext, u16, u8),
+    // get full string descriptor for the given index, of len n
+    StringDesc(StringContext, u16, u8, u8),
+    // get configuration descriptor length n of m. Last u8: attempts so far.
+    ConfigDescLen(u8, u8, ConfigurationValues, u8),
+    // get full configuration descriptor n of m, of wTotalLength bytes.
+    // Last u8: attempts so far. Descriptors are only forwarded to drivers
+    // here, not claimed: the configuration walked here may not be the one
+    // that ends up selected.
+    ConfigDesc(u8, u8, ConfigurationValues, u16, u8),
+    // re-fetch and walk the chosen configuration's descriptor set to let
+    // drivers claim interfaces/endpoints, now that selection is final.
+    // Fields: configuration index n, bConfigurationValue, wTotalLength,
+    // claimed drivers, attempts so far.
+    ClaimConfig(u8, u8, u16, ClaimedDrivers, u8),
+    // SET_CONFIGURATION issued with the chosen bConfigurationValue, awaiting
+    // the status stage to complete. Last u8: attempts so far.
+    SetConfig(u8, ClaimedDrivers, u8),
     // finished discovery.
     Done,
-    // failed to parse one of the descriptors
-    ParseError,
+    // a mandatory request exhausted its retry budget, or a descriptor failed
+    // to parse; the host is notified via `fail_discovery` and should
+    // re-address the device and restart discovery to recover.
+    Failed,
 }
 
 /// Begin discovery, by requesting the device descriptor
@@ -36,7 +160,136 @@ pub fn start_discovery<B: HostBus>(
     )
     .ok()
     .unwrap();
-    DiscoveryState::DeviceDesc
+    DiscoveryState::DeviceDesc(0)
+}
+
+/// Decode a UTF-16LE string descriptor payload (header already stripped)
+/// into lossy ASCII, for `no_std` consumers that cannot allocate a `String`.
+fn decode_string_descriptor(data: &[u8], buf: &mut [u8; MAX_STRING_CHARS]) -> usize {
+    let mut len = 0;
+    for chunk in data.chunks_exact(2).take(MAX_STRING_CHARS) {
+        let code_unit = u16::from_le_bytes([chunk[0], chunk[1]]);
+        buf[len] = if code_unit < 0x80 { code_unit as u8 } else { b'?' };
+        len += 1;
+    }
+    len
+}
+
+/// Move a device into the terminal-but-recoverable `Failed` state, letting
+/// the host know discovery could not complete so it can re-address the
+/// device and restart discovery. Used for both exhausted retry budgets and
+/// unparseable descriptors: neither is worth wedging discovery over.
+fn fail<B: HostBus>(dev_addr: DeviceAddress, host: &mut UsbHost<B>) -> DiscoveryState {
+    host.fail_discovery(dev_addr);
+    DiscoveryState::Failed
+}
+
+/// Issue the first configuration-descriptor-length request (n=0). Shared by
+/// the no-strings and post-string-fetch paths out of `DeviceDesc`.
+fn request_config_desc_len_0<B: HostBus>(
+    dev_addr: DeviceAddress,
+    num_configurations: u8,
+    host: &mut UsbHost<B>,
+) -> DiscoveryState {
+    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+    host.get_descriptor(
+        Some(dev_addr),
+        None,
+        Recipient::Device,
+        descriptor::TYPE_CONFIGURATION,
+        0,
+        9,
+    )
+    .ok()
+    .unwrap();
+    trace!("-> ConfigDescLen(0, {})", num_configurations);
+    DiscoveryState::ConfigDescLen(0, num_configurations, ConfigurationValues::new(), 0)
+}
+
+/// Request the next pending string descriptor, or fall through to the
+/// configuration phase once the queue is empty.
+fn advance_string_fetch<B: HostBus>(
+    dev_addr: DeviceAddress,
+    mut ctx: StringContext,
+    langid: u16,
+    host: &mut UsbHost<B>,
+) -> DiscoveryState {
+    match ctx.next_index() {
+        Some(index) => {
+            // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+            host.get_descriptor(
+                Some(dev_addr),
+                Some(langid),
+                Recipient::Device,
+                descriptor::TYPE_STRING,
+                index,
+                2,
+            )
+            .ok()
+            .unwrap();
+            trace!("-> StringDescLen({}, {})", langid, index);
+            DiscoveryState::StringDescLen(ctx, langid, index)
+        }
+        None => request_config_desc_len_0(dev_addr, ctx.num_configurations, host),
+    }
+}
+
+/// Let drivers pick a configuration by `bConfigurationValue`, defaulting to
+/// the first configuration if none responds, then re-fetch just that
+/// configuration's descriptor set so interfaces/endpoints can be claimed
+/// against the configuration that is actually about to be applied.
+fn select_configuration<B: HostBus>(
+    dev_addr: DeviceAddress,
+    configs: ConfigurationValues,
+    drivers: &mut [&mut dyn Driver<B>],
+    host: &mut UsbHost<B>,
+) -> DiscoveryState {
+    // Every path here has pushed at least configuration 0 (a configuration
+    // descriptor that fails to parse fails discovery before this point), but
+    // don't index a default out of an empty set if that ever changes.
+    if configs.as_slice().is_empty() {
+        trace!("No configuration descriptors were parsed, giving up on device");
+        return fail(dev_addr, host);
+    }
+
+    let mut chosen = None;
+    for driver in &mut *drivers {
+        if let Some(value) = driver.configure(dev_addr, configs.as_slice()) {
+            chosen = Some(value);
+            break;
+        }
+    }
+    // A driver asking for a value we never saw falls back to the first configuration.
+    let n = chosen.and_then(|value| configs.position_of(value)).unwrap_or(0);
+    let (value, total_length) = configs.entry(n);
+
+    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+    host.get_descriptor(
+        Some(dev_addr),
+        None,
+        Recipient::Device,
+        descriptor::TYPE_CONFIGURATION,
+        n,
+        total_length,
+    )
+    .ok()
+    .unwrap();
+    trace!("-> ClaimConfig({})", value);
+    DiscoveryState::ClaimConfig(n, value, total_length, 0, 0)
+}
+
+/// Issue `SET_CONFIGURATION` for the configuration whose interfaces/endpoints
+/// have just been claimed.
+fn set_configuration<B: HostBus>(
+    dev_addr: DeviceAddress,
+    value: u8,
+    claimed: ClaimedDrivers,
+    host: &mut UsbHost<B>,
+) -> DiscoveryState {
+    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+    host.set_configuration(Some(dev_addr), value).ok().unwrap();
+    trace!("-> SetConfig({})", value);
+    DiscoveryState::SetConfig(value, claimed, 0)
 }
 
 pub fn process_discovery<B: HostBus>(
@@ -47,51 +300,163 @@ pub fn process_discovery<B: HostBus>(
     host: &mut UsbHost<B>,
 ) -> DiscoveryState {
     match state {
-        DiscoveryState::DeviceDesc => {
+        DiscoveryState::DeviceDesc(attempts) => {
             match event {
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    if attempts + 1 >= MAX_ATTEMPTS {
+                        trace!("Device descriptor request failed after {} attempts", attempts + 1);
+                        return fail(dev_addr, host);
+                    }
+                    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+                    host.get_descriptor(
+                        Some(dev_addr),
+                        None,
+                        Recipient::Device,
+                        descriptor::TYPE_DEVICE,
+                        0,
+                        18,
+                    )
+                    .ok()
+                        .unwrap();
+                    DiscoveryState::DeviceDesc(attempts + 1)
+                }
                 Event::ControlInData(_, length) => {
                     let data = host.bus.received_data(length as usize);
                     let Ok((_, descriptor)) = descriptor::parse::any_descriptor(data) else {
                         trace!("Failed to parse descriptor frame: {}", data);
-                        return DiscoveryState::ParseError
+                        return fail(dev_addr, host)
                     };
                     for driver in drivers {
                         driver.descriptor(dev_addr, descriptor.descriptor_type, descriptor.data);
                     }
                     let Ok((_, device_descriptor)) = descriptor::parse::device_descriptor(descriptor.data) else {
                         trace!("Failed to parse device descriptor: {}", descriptor.data);
-                        return DiscoveryState::ParseError
+                        return fail(dev_addr, host)
                     };
 
+                    let has_strings = device_descriptor.i_manufacturer != 0
+                        || device_descriptor.i_product != 0
+                        || device_descriptor.i_serial_number != 0;
+
+                    if !has_strings {
+                        return request_config_desc_len_0(dev_addr, device_descriptor.num_configurations, host);
+                    }
+
+                    let ctx = StringContext::new(
+                        device_descriptor.i_manufacturer,
+                        device_descriptor.i_product,
+                        device_descriptor.i_serial_number,
+                        device_descriptor.num_configurations,
+                    );
                     // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
                     host.get_descriptor(
                         Some(dev_addr),
                         None,
                         Recipient::Device,
-                        descriptor::TYPE_CONFIGURATION,
+                        descriptor::TYPE_STRING,
                         0,
-                        9,
+                        255,
                     )
                     .ok()
                         .unwrap();
-                    trace!("-> ConfigDescLen(0, {})", device_descriptor.num_configurations);
-                    DiscoveryState::ConfigDescLen(0, device_descriptor.num_configurations)
+                    trace!("-> StringLangId");
+                    DiscoveryState::StringLangId(ctx)
                 }
                 _ => state,
             }
         }
-        DiscoveryState::ConfigDescLen(n, m) => {
+        DiscoveryState::StringLangId(ctx) => {
             match event {
                 Event::ControlInData(_, length) => {
                     let data = host.bus.received_data(length as usize);
                     let Ok((_, descriptor)) = descriptor::parse::any_descriptor(data) else {
                         trace!("Failed to parse descriptor frame: {}", data);
-                        return DiscoveryState::ParseError
+                        return fail(dev_addr, host)
                     };
-                    let Ok((_, total_length)) = descriptor::parse::configuration_descriptor_length(descriptor.data) else {
-                        trace!("Failed to extract length from configuration descriptor: {}", descriptor.data);
-                        return DiscoveryState::ParseError
+                    // The LANGID array is a run of little-endian u16s; fall back to
+                    // no language id (0) if the device reported an empty list.
+                    let langid = descriptor.data
+                        .chunks_exact(2)
+                        .next()
+                        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                        .unwrap_or(0);
+                    advance_string_fetch(dev_addr, ctx, langid, host)
+                }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    trace!("Device stalled LANGID request, skipping strings");
+                    request_config_desc_len_0(dev_addr, ctx.num_configurations, host)
+                }
+                _ => state,
+            }
+        }
+        DiscoveryState::StringDescLen(ctx, langid, index) => {
+            match event {
+                Event::ControlInData(_, length) => {
+                    let data = host.bus.received_data(length as usize);
+                    // A short read here is no more fatal than a STALL: the string is optional,
+                    // so skip it and move on rather than wedging discovery on a ParseError.
+                    let Some(&total_length) = data.get(0) else {
+                        trace!("Short string {} length read, skipping", index);
+                        return advance_string_fetch(dev_addr, ctx, langid, host)
+                    };
+                    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+                    host.get_descriptor(
+                        Some(dev_addr),
+                        Some(langid),
+                        Recipient::Device,
+                        descriptor::TYPE_STRING,
+                        index,
+                        total_length as u16,
+                    )
+                    .ok()
+                        .unwrap();
+                    trace!("-> StringDesc({}, {}, {})", langid, index, total_length);
+                    DiscoveryState::StringDesc(ctx, langid, index, total_length)
+                }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    trace!("Device stalled string {} length request, skipping", index);
+                    advance_string_fetch(dev_addr, ctx, langid, host)
+                }
+                _ => state,
+            }
+        }
+        DiscoveryState::StringDesc(ctx, langid, index, _len) => {
+            match event {
+                Event::ControlInData(_, length) => {
+                    let data = host.bus.received_data(length as usize);
+                    let Ok((_, descriptor)) = descriptor::parse::any_descriptor(data) else {
+                        trace!("Failed to parse descriptor frame: {}", data);
+                        return fail(dev_addr, host)
+                    };
+                    let mut buf = [0u8; MAX_STRING_CHARS];
+                    let decoded_len = decode_string_descriptor(descriptor.data, &mut buf);
+                    // Unwrap safety: `decode_string_descriptor` only ever writes ASCII bytes.
+                    let text = core::str::from_utf8(&buf[..decoded_len]).unwrap();
+                    for driver in drivers {
+                        driver.string(dev_addr, index, text);
+                    }
+                    advance_string_fetch(dev_addr, ctx, langid, host)
+                }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    trace!("Device stalled string {} descriptor read, skipping", index);
+                    advance_string_fetch(dev_addr, ctx, langid, host)
+                }
+                _ => state,
+            }
+        }
+        DiscoveryState::ConfigDescLen(n, m, mut configs, attempts) => {
+            match event {
+                Event::ControlInData(_, length) => {
+                    let data = host.bus.received_data(length as usize);
+                    let Ok((_, descriptor)) = descriptor::parse::any_descriptor(data) else {
+                        trace!("Failed to parse descriptor frame: {}", data);
+                        return fail(dev_addr, host)
+                    };
+                    let Ok((_, config_descriptor)) = descriptor::parse::configuration_descriptor(descriptor.data) else {
+                        trace!("Failed to parse configuration descriptor: {}", descriptor.data);
+                        return fail(dev_addr, host)
                     };
+                    configs.push(config_descriptor.configuration_value, config_descriptor.total_length);
                     // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
                     host.get_descriptor(
                         Some(dev_addr),
@@ -99,24 +464,50 @@ pub fn process_discovery<B: HostBus>(
                         Recipient::Device,
                         descriptor::TYPE_CONFIGURATION,
                         n,
-                        total_length,
+                        config_descriptor.total_length,
                     )
                     .ok()
                         .unwrap();
                     trace!("-> ConfigDesc({}, {})", n, m);
-                    DiscoveryState::ConfigDesc(n, m)
+                    DiscoveryState::ConfigDesc(n, m, configs, config_descriptor.total_length, 0)
+                }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    if attempts + 1 < MAX_ATTEMPTS {
+                        // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+                        host.get_descriptor(
+                            Some(dev_addr),
+                            None,
+                            Recipient::Device,
+                            descriptor::TYPE_CONFIGURATION,
+                            n,
+                            9,
+                        )
+                        .ok()
+                            .unwrap();
+                        return DiscoveryState::ConfigDescLen(n, m, configs, attempts + 1);
+                    }
+                    if n == 0 {
+                        trace!("Configuration descriptor length request failed after {} attempts", attempts + 1);
+                        return fail(dev_addr, host);
+                    }
+                    // n > 0 is an extra, optional configuration: proceed with what we have.
+                    trace!("Configuration {} length request failed, skipping remaining configurations", n);
+                    select_configuration(dev_addr, configs, drivers, host)
                 }
                 _ => state,
             }
         }
-        DiscoveryState::ConfigDesc(n, m) => {
+        DiscoveryState::ConfigDesc(n, m, configs, total_length, attempts) => {
             match event {
                 Event::ControlInData(_, length) => {
                     let mut data = host.bus.received_data(length as usize);
+                    // Every descriptor in every configuration is still forwarded for
+                    // observability; interface/endpoint claiming happens later, only
+                    // for the configuration that is actually selected (see `ClaimConfig`).
                     loop {
                         let Ok((rest, descriptor)) = descriptor::parse::any_descriptor(data) else {
                             trace!("Failed to parse descriptor frame: {}", data);
-                            return DiscoveryState::ParseError
+                            return fail(dev_addr, host)
                         };
                         for driver in &mut *drivers {
                             driver.descriptor(
@@ -144,16 +535,130 @@ pub fn process_discovery<B: HostBus>(
                         .ok()
                         .unwrap();
                         trace!("-> ConfigDescLen({}, {})", n + 1, m);
-                        DiscoveryState::ConfigDescLen(n + 1, m)
+                        DiscoveryState::ConfigDescLen(n + 1, m, configs, 0)
                     } else {
-                        // NOTE: do not start a transfer here, the UsbHost code expects the bus to stay idle.
-                        trace!("-> Done");
-                        DiscoveryState::Done
+                        select_configuration(dev_addr, configs, drivers, host)
                     }
                 }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    if attempts + 1 < MAX_ATTEMPTS {
+                        // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+                        host.get_descriptor(
+                            Some(dev_addr),
+                            None,
+                            Recipient::Device,
+                            descriptor::TYPE_CONFIGURATION,
+                            n,
+                            total_length,
+                        )
+                        .ok()
+                            .unwrap();
+                        return DiscoveryState::ConfigDesc(n, m, configs, total_length, attempts + 1);
+                    }
+                    if n == 0 {
+                        trace!("Configuration descriptor request failed after {} attempts", attempts + 1);
+                        return fail(dev_addr, host);
+                    }
+                    // n > 0 is an extra, optional configuration: proceed with what we have.
+                    trace!("Configuration {} request failed, skipping remaining configurations", n);
+                    select_configuration(dev_addr, configs, drivers, host)
+                }
+                _ => state,
+            }
+        }
+        DiscoveryState::ClaimConfig(n, value, total_length, mut claimed, attempts) => {
+            match event {
+                Event::ControlInData(_, length) => {
+                    let mut data = host.bus.received_data(length as usize);
+                    // Endpoint descriptors are scoped by the interface descriptor
+                    // preceding them; track both as we walk the descriptor set.
+                    let mut current_interface = None;
+                    let mut claiming_driver = None;
+                    loop {
+                        let Ok((rest, descriptor)) = descriptor::parse::any_descriptor(data) else {
+                            trace!("Failed to parse descriptor frame: {}", data);
+                            return fail(dev_addr, host)
+                        };
+                        if descriptor.descriptor_type == descriptor::TYPE_INTERFACE {
+                            // Reset scope unconditionally: a malformed interface descriptor
+                            // must not leave endpoints attributed to the previous interface.
+                            current_interface = None;
+                            claiming_driver = None;
+                            if let Ok((_, interface)) = descriptor::parse::interface_descriptor(descriptor.data) {
+                                for (i, driver) in drivers.iter_mut().enumerate().take(32) {
+                                    if driver.want_interface(dev_addr, &interface) {
+                                        claiming_driver = Some(i);
+                                        claimed |= 1 << i;
+                                        break;
+                                    }
+                                }
+                                current_interface = Some(interface);
+                            }
+                        } else if descriptor.descriptor_type == descriptor::TYPE_ENDPOINT {
+                            if let (Some(interface), Some(i)) = (current_interface, claiming_driver) {
+                                if let Ok((_, endpoint)) = descriptor::parse::endpoint_descriptor(descriptor.data) {
+                                    drivers[i].endpoint(dev_addr, &interface, &endpoint);
+                                }
+                            }
+                        }
+                        if rest.len() > 0 {
+                            data = rest;
+                        } else {
+                            break;
+                        }
+                    }
+                    set_configuration(dev_addr, value, claimed, host)
+                }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    if attempts + 1 >= MAX_ATTEMPTS {
+                        trace!("Claim pass for configuration {} failed after {} attempts", value, attempts + 1);
+                        return fail(dev_addr, host);
+                    }
+                    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+                    host.get_descriptor(
+                        Some(dev_addr),
+                        None,
+                        Recipient::Device,
+                        descriptor::TYPE_CONFIGURATION,
+                        n,
+                        total_length,
+                    )
+                    .ok()
+                        .unwrap();
+                    DiscoveryState::ClaimConfig(n, value, total_length, claimed, attempts + 1)
+                }
+                _ => state,
+            }
+        }
+        DiscoveryState::SetConfig(value, claimed, attempts) => {
+            match event {
+                Event::ControlOutDone(_) => {
+                    for (i, driver) in drivers.iter_mut().enumerate().take(32) {
+                        if claimed & (1 << i) != 0 {
+                            driver.configured(dev_addr);
+                        }
+                    }
+                    trace!("Configuration {} applied, -> Done", value);
+                    DiscoveryState::Done
+                }
+                Event::ControlError(_) | Event::Timeout(_) => {
+                    if attempts + 1 >= MAX_ATTEMPTS {
+                        trace!("SET_CONFIGURATION({}) failed after {} attempts", value, attempts + 1);
+                        return fail(dev_addr, host);
+                    }
+                    // Unwrap safety: when a `Control*` event is emitted, the host is idle and a transfer can be started
+                    host.set_configuration(Some(dev_addr), value).ok().unwrap();
+                    DiscoveryState::SetConfig(value, claimed, attempts + 1)
+                }
                 _ => state,
             }
         }
-        DiscoveryState::Done | DiscoveryState::ParseError => unreachable!(),
+        // A well-behaved host stops driving events into a finished device, but
+        // a stray late event racing the host's teardown shouldn't panic.
+        DiscoveryState::Done => state,
+        // `Failed` is recoverable: the host is expected to re-address the
+        // device and call `start_discovery` again rather than keep feeding
+        // events into this state, but ignore any that arrive anyway.
+        DiscoveryState::Failed => state,
     }
 }